@@ -0,0 +1,94 @@
+use serde::Serialize;
+use tokio_postgres::Client;
+
+#[derive(Debug, Serialize)]
+pub struct AlbumListItem {
+    item_id: String,
+    ts: i64,
+    portrait: bool,
+}
+
+pub async fn list(dbclient: &Client) -> Result<Vec<AlbumListItem>, tokio_postgres::Error> {
+    let rows = dbclient
+        .query("SELECT item_id, ts, portrait FROM album ORDER BY item_id", &[])
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| AlbumListItem {
+            item_id: row.get(0),
+            ts: row.get(1),
+            portrait: row.get(2),
+        })
+        .collect())
+}
+
+pub async fn delete(dbclient: &Client, item_id: &str) -> Result<u64, tokio_postgres::Error> {
+    dbclient
+        .execute("DELETE FROM album WHERE item_id = $1", &[&item_id])
+        .await
+}
+
+/// Inserts a new album entry with freshly packed EPD `data`, returning the
+/// generated `item_id`. `ts` is set to one below the minimum of the existing
+/// album so the new image is served on the very next `/frame` request.
+pub async fn create(
+    dbclient: &Client,
+    data: Vec<u8>,
+    portrait: bool,
+) -> Result<String, tokio_postgres::Error> {
+    let item_id = uuid::Uuid::new_v4().to_string();
+    dbclient
+        .execute(
+            "INSERT INTO album (item_id, ts, portrait, data)
+            VALUES ($1, (SELECT COALESCE(MIN(ts), 0) - 1 FROM album), $2, $3)",
+            &[&item_id, &portrait, &data],
+        )
+        .await?;
+    Ok(item_id)
+}
+
+/// Floyd–Steinberg-dithers `image` down to the panel's 1-bit-per-pixel
+/// palette and packs it 2 pixels per byte (high nibble first), matching the
+/// layout already used for the `/frame` merge routine. `0b0001` is white,
+/// `0b0000` is black, per the existing packed format.
+pub fn dither_to_palette(image: &image::DynamicImage, width: u32, height: u32) -> Vec<u8> {
+    let resized = image
+        .resize_exact(width, height, image::imageops::FilterType::Lanczos3)
+        .to_luma8();
+    let w = width as usize;
+    let h = height as usize;
+    let mut errors: Vec<f32> = resized.pixels().map(|p| p[0] as f32).collect();
+    let mut bits = vec![0u8; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let i = y * w + x;
+            let old = errors[i];
+            let new = if old > 127.5 { 1.0 } else { 0.0 };
+            bits[i] = new as u8;
+            let error = old - new * 255.0;
+            if x + 1 < w {
+                errors[i + 1] += error * 7.0 / 16.0;
+            }
+            if y + 1 < h {
+                if x > 0 {
+                    errors[i + w - 1] += error * 3.0 / 16.0;
+                }
+                errors[i + w] += error * 5.0 / 16.0;
+                if x + 1 < w {
+                    errors[i + w + 1] += error * 1.0 / 16.0;
+                }
+            }
+        }
+    }
+    let packed_width = w / 2;
+    let mut packed = vec![0u8; packed_width * h];
+    let nibble = |bit: u8| if bit == 1 { 0b0001 } else { 0b0000 };
+    for y in 0..h {
+        for x in 0..packed_width {
+            let hi = nibble(bits[y * w + x * 2]);
+            let lo = nibble(bits[y * w + x * 2 + 1]);
+            packed[y * packed_width + x] = (hi << 4) | lo;
+        }
+    }
+    packed
+}
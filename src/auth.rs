@@ -0,0 +1,44 @@
+use tokio_postgres::Client;
+
+/// What a presented API key is allowed to do. `Read` covers `/frame` and
+/// `/metrics`; `Admin` additionally covers the `/album` management routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Read,
+    Admin,
+}
+
+impl Scope {
+    /// Whether a key with `granted` scope satisfies a route that `required`s
+    /// this scope. Admin implies read.
+    fn satisfies(granted: &str, required: Scope) -> bool {
+        match (granted, required) {
+            ("admin", _) => true,
+            ("read", Scope::Read) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Looks up `presented_key` in the `api_keys` table and reports whether its
+/// granted scope covers `required`. A key that doesn't exist is simply
+/// unauthorized, not an error.
+pub async fn authorize(
+    dbclient: &Client,
+    presented_key: &str,
+    required: Scope,
+) -> Result<bool, tokio_postgres::Error> {
+    let row = dbclient
+        .query_opt(
+            "SELECT scope FROM api_keys WHERE key = $1",
+            &[&presented_key],
+        )
+        .await?;
+    Ok(match row {
+        Some(row) => {
+            let granted: String = row.get(0);
+            Scope::satisfies(&granted, required)
+        }
+        None => false,
+    })
+}
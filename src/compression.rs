@@ -0,0 +1,40 @@
+use crate::config::CompressionConfig;
+
+/// Compresses `data` when the client's `Accept-Encoding` advertises `gzip` or
+/// `deflate` and the body clears `config.min_size_bytes`, returning the body
+/// to send and the `Content-Encoding` value to set, if any. Falls back to
+/// sending `data` unchanged when the header is absent or the encoded form
+/// isn't actually smaller.
+pub fn negotiate(
+    data: Vec<u8>,
+    accept_encoding: &str,
+    config: &CompressionConfig,
+) -> (Vec<u8>, Option<&'static str>) {
+    if data.len() < config.min_size_bytes {
+        return (data, None);
+    }
+    let level = flate2::Compression::new(config.level);
+    let encoded = if accept_encoding.contains("gzip") {
+        encode_gzip(&data, level).map(|body| (body, "gzip"))
+    } else if accept_encoding.contains("deflate") {
+        encode_deflate(&data, level).map(|body| (body, "deflate"))
+    } else {
+        None
+    };
+    match encoded {
+        Some((body, encoding)) if body.len() < data.len() => (body, Some(encoding)),
+        _ => (data, None),
+    }
+}
+
+fn encode_gzip(data: &[u8], level: flate2::Compression) -> Option<Vec<u8>> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+    std::io::Write::write_all(&mut encoder, data).ok()?;
+    encoder.finish().ok()
+}
+
+fn encode_deflate(data: &[u8], level: flate2::Compression) -> Option<Vec<u8>> {
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), level);
+    std::io::Write::write_all(&mut encoder, data).ok()?;
+    encoder.finish().ok()
+}
@@ -0,0 +1,132 @@
+use serde::Deserialize;
+use std::{env, fs, net::SocketAddr};
+
+/// Pixels are packed 2-per-byte, so the panel width must split evenly into
+/// whole bytes on both halves of the merge routine.
+const PACKING_FACTOR: u32 = 4;
+
+/// Which line format the access log writes.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Apache-combined-style text line: address, time, request line, status
+    /// and bytes, followed by latency and `chip_id`. Unlike the historical
+    /// line, it has no referer or user-agent and always reports `HTTP/1.1`.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Access-log settings: where to write, in what format, and when to rotate.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    pub stdout: bool,
+    pub file: Option<String>,
+    pub format: LogFormat,
+    /// Rotate the log file once it grows past this many bytes. `None` disables
+    /// size-based rotation.
+    pub rotate_max_bytes: Option<u64>,
+    /// Rotate the log file at midnight local time.
+    pub rotate_daily: bool,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            stdout: true,
+            file: None,
+            format: LogFormat::Combined,
+            rotate_max_bytes: Some(100 * 1024 * 1024),
+            rotate_daily: true,
+        }
+    }
+}
+
+/// Response-compression settings for the `/frame` payload.
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Bodies smaller than this are sent uncompressed; DEFLATE/gzip framing
+    /// overhead isn't worth it for tiny payloads.
+    pub min_size_bytes: usize,
+    /// flate2 compression level, 0 (none) to 9 (best).
+    pub level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            min_size_bytes: 256,
+            level: 6,
+        }
+    }
+}
+
+/// Server configuration, loaded from a TOML file (path via the first CLI
+/// argument or the `CONFIG_PATH` env var) and layered over built-in
+/// defaults. `secrets/.env` is still consulted separately for
+/// `POSTGRES_CONNECTION_STRING`, which stays env-only rather than living in
+/// the config file. API keys now live in the `api_keys` table rather than
+/// the environment; see `auth`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub bind_address: SocketAddr,
+    pub pool_size: usize,
+    pub worker_threads: usize,
+    pub panel_width: u32,
+    pub panel_height: u32,
+    pub access_log: LogConfig,
+    pub compression: CompressionConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_address: "0.0.0.0:5000".parse().expect("valid default"),
+            pool_size: 2,
+            worker_threads: 2,
+            panel_width: 600,
+            panel_height: 448,
+            access_log: LogConfig::default(),
+            compression: CompressionConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file named by the first CLI argument or `CONFIG_PATH`,
+    /// falling back to defaults if neither is set. A file that is named but
+    /// missing or malformed is a startup error, not a silent fallback.
+    pub fn load() -> Result<Config, String> {
+        let path = env::args().nth(1).or_else(|| env::var("CONFIG_PATH").ok());
+        let config = match path {
+            Some(path) => {
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| format!("could not read config file {}: {}", path, e))?;
+                toml::from_str(&contents)
+                    .map_err(|e| format!("malformed config file {}: {}", path, e))?
+            }
+            None => Config::default(),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.panel_width % PACKING_FACTOR != 0 {
+            return Err(format!(
+                "panel_width ({}) must be divisible by {}",
+                self.panel_width, PACKING_FACTOR
+            ));
+        }
+        if self.pool_size == 0 {
+            return Err("pool_size must be at least 1".to_string());
+        }
+        if self.worker_threads == 0 {
+            return Err("worker_threads must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
@@ -1,45 +1,351 @@
 use lazy_static::lazy_static;
-use postgres::{Client, NoTls};
-use std::{collections::VecDeque, sync::Mutex};
+use postgres_rustls::MakeRustlsConnect;
+use std::collections::VecDeque;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify, OnceCell};
+use tokio::time::Instant;
+use tokio_postgres::{Client, NoTls};
+
+/// Skip the liveness probe for a connection that was returned to the pool
+/// more recently than this, to avoid a round-trip on every checkout.
+const PROBE_GRACE_PERIOD: Duration = Duration::from_secs(30);
+/// How many times to retry a dead connection with a fresh one before giving up.
+const MAX_RECONNECT_ATTEMPTS: usize = 3;
+/// How long `get_client` blocks waiting for a client to become available
+/// before giving up, unless overridden by `POOL_ACQUIRE_TIMEOUT_SECS`.
+const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a connection may live before it's retired and replaced, unless
+/// overridden by `POOL_MAX_LIFETIME_SECS`.
+const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(30 * 60);
 
 lazy_static! {
-    pub static ref CONNECTION_POOL: Mutex<VecDeque<Client>> = {
+    pub static ref CONNECTION_POOL: Pool = {
         log::info!("empty pool created");
-        Mutex::new(VecDeque::<Client>::new())
+        Pool {
+            clients: Mutex::new(VecDeque::new()),
+            notify: Notify::new(),
+            database_url: OnceCell::new(),
+            tls: OnceCell::new(),
+            settings: OnceCell::new(),
+        }
     };
 }
 
-impl CONNECTION_POOL {
-    pub fn initialise(&self, database_url: &str, pool_size: usize) -> Result<(), postgres::Error> {
-        let mut pool = self.lock().unwrap();
-        for _ in pool.len()..pool_size {
-            match Client::connect(database_url, NoTls) {
-                Ok(client) => pool.push_back(client),
-                Err(e) => {
-                    log::error!("failed to create connection: {:?}", e);
-                    return Err(e);
-                }
-            }
+/// How the pool should negotiate TLS with Postgres, set once at startup from
+/// `POSTGRES_TLS` (`require` | `prefer` | `disable`, default `disable`) and an
+/// optional `POSTGRES_CA_CERT` path. The `rustls::ClientConfig` is built once
+/// and cheaply cloned into a fresh `MakeRustlsConnect` for every connection.
+enum TlsSetting {
+    Disable,
+    Prefer(Arc<rustls::ClientConfig>),
+    Require(Arc<rustls::ClientConfig>),
+}
+
+fn build_tls_setting() -> Result<TlsSetting, std::io::Error> {
+    let mode = env::var("POSTGRES_TLS").unwrap_or_else(|_| "disable".to_string());
+    if mode.eq_ignore_ascii_case("disable") {
+        return Ok(TlsSetting::Disable);
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    if let Ok(ca_cert_path) = env::var("POSTGRES_CA_CERT") {
+        let pem = std::fs::read(&ca_cert_path)?;
+        for cert in rustls_pemfile::certs(&mut pem.as_slice()) {
+            let cert = cert.map_err(|e| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("malformed POSTGRES_CA_CERT at {}: {}", ca_cert_path, e),
+                )
+            })?;
+            roots
+                .add(cert)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        }
+    } else {
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    }
+    let config = Arc::new(
+        rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    );
+
+    if mode.eq_ignore_ascii_case("require") {
+        Ok(TlsSetting::Require(config))
+    } else if mode.eq_ignore_ascii_case("prefer") {
+        Ok(TlsSetting::Prefer(config))
+    } else {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid POSTGRES_TLS value: {} (expected require|prefer|disable)", mode),
+        ))
+    }
+}
+
+/// Runtime knobs read once from the environment at `initialise` time.
+struct PoolSettings {
+    acquire_timeout: Duration,
+    max_lifetime: Duration,
+}
+
+fn build_pool_settings() -> PoolSettings {
+    let acquire_timeout = env::var("POOL_ACQUIRE_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_ACQUIRE_TIMEOUT);
+    let max_lifetime = env::var("POOL_MAX_LIFETIME_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_LIFETIME);
+    PoolSettings {
+        acquire_timeout,
+        max_lifetime,
+    }
+}
+
+struct Entry {
+    client: Client,
+    created_at: Instant,
+    returned_at: Instant,
+}
+
+/// Async connection pool backed by `tokio_postgres`. Connections are handed
+/// out as an RAII [`PooledClient`] guard that returns itself to the queue
+/// when dropped, instead of requiring callers to remember to release it.
+/// `get_client` blocks (up to `acquire_timeout`) for a client to free up
+/// instead of failing immediately, checkout validates the connection with a
+/// cheap probe and transparently reconnects a dead one, and connections
+/// older than `max_lifetime` are retired and replaced.
+pub struct Pool {
+    clients: Mutex<VecDeque<Entry>>,
+    notify: Notify,
+    database_url: OnceCell<String>,
+    tls: OnceCell<TlsSetting>,
+    settings: OnceCell<PoolSettings>,
+}
+
+impl Pool {
+    pub async fn initialise(&self, database_url: &str, pool_size: usize) -> Result<(), std::io::Error> {
+        self.database_url
+            .set(database_url.to_string())
+            .unwrap_or(());
+        if self.tls.get().is_none() {
+            self.tls.set(build_tls_setting()?).unwrap_or(());
+        }
+        self.settings.set(build_pool_settings()).unwrap_or(());
+        let tls = self.tls.get().expect("set above");
+        let mut clients = self.clients.lock().await;
+        for _ in clients.len()..pool_size {
+            let now = Instant::now();
+            clients.push_back(Entry {
+                client: connect(database_url, tls)
+                    .await
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?,
+                created_at: now,
+                returned_at: now,
+            });
         }
         log::info!("connection pool populated, size: {}", pool_size);
         Ok(())
     }
 
-    pub fn get_client(&self) -> Result<Client, std::io::Error> {
-        let mut pool = self.lock().unwrap();
-        match pool.pop_front() {
-            Some(client) => return Ok(client),
-            None => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "connection pool is exhausted",
-                ))
+    pub async fn get_client(&self) -> Result<PooledClient, std::io::Error> {
+        let acquire_started = Instant::now();
+        let result = self.get_client_inner().await;
+        crate::metrics::METRICS.record_pool_acquire(
+            acquire_started.elapsed(),
+            matches!(&result, Err(e) if e.kind() == std::io::ErrorKind::TimedOut),
+        );
+        result
+    }
+
+    async fn get_client_inner(&self) -> Result<PooledClient, std::io::Error> {
+        let database_url = self
+            .database_url
+            .get()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "pool not initialised"))?;
+        let tls = self
+            .tls
+            .get()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "pool not initialised"))?;
+        let settings = self
+            .settings
+            .get()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Other, "pool not initialised"))?;
+        let deadline = Instant::now() + settings.acquire_timeout;
+
+        loop {
+            let entry = {
+                let mut clients = self.clients.lock().await;
+                clients.pop_front()
+            };
+            let (client, created_at) = match entry {
+                Some(entry) if entry.created_at.elapsed() > settings.max_lifetime => {
+                    log::info!("retiring connection past max_lifetime, reconnecting");
+                    (reconnect(database_url, tls).await?, Instant::now())
+                }
+                Some(entry) if entry.returned_at.elapsed() < PROBE_GRACE_PERIOD => {
+                    (entry.client, entry.created_at)
+                }
+                Some(entry) => {
+                    if is_valid(&entry.client).await {
+                        (entry.client, entry.created_at)
+                    } else {
+                        log::warn!("discarding dead connection, reconnecting");
+                        (reconnect(database_url, tls).await?, Instant::now())
+                    }
+                }
+                None => {
+                    let timeout = tokio::time::timeout_at(deadline, self.notify.notified()).await;
+                    if timeout.is_err() {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "timed out waiting for a connection from the pool",
+                        ));
+                    }
+                    continue;
+                }
+            };
+            return Ok(PooledClient {
+                client: Some(client),
+                created_at,
+                poisoned: false,
+            });
+        }
+    }
+
+    /// Drains and drops every idle connection, closing them, so the pool can
+    /// be shut down cleanly instead of letting the process exit underneath
+    /// them. Call once, after the server has stopped accepting new work.
+    pub async fn shutdown(&self) {
+        let mut clients = self.clients.lock().await;
+        let closed = clients.len();
+        clients.clear();
+        log::info!("connection pool drained, closed {} connection(s)", closed);
+    }
+
+    async fn release_client(&self, client: Client, created_at: Instant, poisoned: bool) {
+        if poisoned || client.is_closed() {
+            log::warn!("dropping poisoned connection instead of returning it to the pool");
+            if let (Some(database_url), Some(tls)) = (self.database_url.get(), self.tls.get()) {
+                match connect(database_url, tls).await {
+                    Ok(replacement) => {
+                        let mut clients = self.clients.lock().await;
+                        clients.push_back(Entry {
+                            client: replacement,
+                            created_at: Instant::now(),
+                            returned_at: Instant::now(),
+                        });
+                    }
+                    Err(e) => log::error!("could not replace discarded connection: {}", e),
+                }
+            }
+        } else {
+            let mut clients = self.clients.lock().await;
+            clients.push_back(Entry {
+                client,
+                created_at,
+                returned_at: Instant::now(),
+            });
+        }
+        self.notify.notify_one();
+    }
+}
+
+async fn is_valid(client: &Client) -> bool {
+    !client.is_closed() && client.simple_query("SELECT 1").await.is_ok()
+}
+
+async fn connect(database_url: &str, tls: &TlsSetting) -> Result<Client, tokio_postgres::Error> {
+    let (client, connection) = match tls {
+        TlsSetting::Disable => tokio_postgres::connect(database_url, NoTls).await?,
+        TlsSetting::Require(config) => {
+            tokio_postgres::connect(database_url, MakeRustlsConnect::new((**config).clone())).await?
+        }
+        TlsSetting::Prefer(config) => {
+            match tokio_postgres::connect(database_url, MakeRustlsConnect::new((**config).clone())).await {
+                Ok(connected) => connected,
+                Err(e) => {
+                    log::warn!("TLS handshake failed ({}), falling back to plaintext", e);
+                    tokio_postgres::connect(database_url, NoTls).await?
+                }
+            }
+        }
+    };
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("connection driver error: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+async fn reconnect(database_url: &str, tls: &TlsSetting) -> Result<Client, std::io::Error> {
+    let mut last_err = None;
+    for attempt in 1..=MAX_RECONNECT_ATTEMPTS {
+        match connect(database_url, tls).await {
+            Ok(client) => return Ok(client),
+            Err(e) => {
+                log::error!("reconnect attempt {} failed: {}", attempt, e);
+                last_err = Some(e);
             }
-        };
+        }
     }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        format!(
+            "failed to reconnect after {} attempts: {}",
+            MAX_RECONNECT_ATTEMPTS,
+            last_err.expect("loop ran at least once")
+        ),
+    ))
+}
+
+/// A [`Client`] checked out of the [`Pool`]. Returns itself to the pool when
+/// dropped, so callers no longer have to remember to call `release_client`.
+/// Call [`PooledClient::poison`] before dropping if the connection errored
+/// during use, so it is discarded (and transparently replaced) rather than
+/// recirculated.
+pub struct PooledClient {
+    client: Option<Client>,
+    created_at: Instant,
+    poisoned: bool,
+}
+
+impl PooledClient {
+    pub fn poison(&mut self) {
+        self.poisoned = true;
+    }
+}
 
-    pub fn release_client(&self, client: Client) {
-        let mut pool = self.lock().unwrap();
-        pool.push_back(client);
+impl std::ops::Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("client taken only on drop")
+    }
+}
+
+impl std::ops::DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().expect("client taken only on drop")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            let created_at = self.created_at;
+            let poisoned = self.poisoned;
+            tokio::spawn(async move {
+                CONNECTION_POOL
+                    .release_client(client, created_at, poisoned)
+                    .await;
+            });
+        }
     }
 }
@@ -0,0 +1,50 @@
+use hyper::StatusCode;
+
+/// The error type threaded through [`crate::try_handle`], so a failure deep
+/// in request handling funnels through a single place that logs it and maps
+/// it to a response, instead of an `.expect()` that would take down the
+/// worker thread it ran on.
+#[derive(Debug)]
+pub enum Error {
+    /// A Postgres error, tagged with what the query was trying to do.
+    Db(&'static str, tokio_postgres::Error),
+    /// Could not get a client from the pool at all.
+    Pool(std::io::Error),
+    /// The request itself was malformed.
+    BadRequest(&'static str),
+}
+
+impl Error {
+    pub fn status_and_message(&self) -> (StatusCode, &'static str) {
+        match self {
+            Error::Db(_, _) | Error::Pool(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error")
+            }
+            Error::BadRequest(_) => (StatusCode::BAD_REQUEST, "Bad request"),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Db(context, e) => write!(f, "{}: {}", context, e),
+            Error::Pool(e) => write!(f, "could not get a connection from the pool: {}", e),
+            Error::BadRequest(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Pool(e)
+    }
+}
+
+/// Whether a Postgres error indicates the connection itself is broken
+/// (as opposed to e.g. a constraint violation on an otherwise healthy
+/// connection), in which case the checked-out client should be poisoned
+/// rather than returned to the pool.
+pub fn is_connection_error(e: &tokio_postgres::Error) -> bool {
+    e.as_db_error().is_none()
+}
@@ -0,0 +1,164 @@
+use crate::config::{LogConfig, LogFormat};
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use ureq::serde_json;
+
+/// One completed request, ready to be formatted by [`AccessLog`].
+pub struct AccessLogEntry<'a> {
+    pub remote_addr: IpAddr,
+    pub method: &'a str,
+    pub uri: &'a str,
+    pub status: u16,
+    pub bytes: usize,
+    pub latency: Duration,
+    /// The `item_id` of the album image a `/frame` request served, if any.
+    /// No device chip/uuid is read from the request.
+    pub chip_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    remote_addr: String,
+    method: &'a str,
+    uri: &'a str,
+    status: u16,
+    bytes: usize,
+    latency_ms: f64,
+    chip_id: Option<&'a str>,
+}
+
+struct RotatingFile {
+    path: String,
+    file: File,
+    size: u64,
+    opened_on: NaiveDate,
+    max_bytes: Option<u64>,
+    rotate_daily: bool,
+}
+
+impl RotatingFile {
+    fn open(path: &str) -> std::io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn new(config: &LogConfig, path: String) -> std::io::Result<Self> {
+        let file = Self::open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            opened_on: Local::now().date_naive(),
+            max_bytes: config.rotate_max_bytes,
+            rotate_daily: config.rotate_daily,
+        })
+    }
+
+    fn rotate_if_needed(&mut self) -> std::io::Result<()> {
+        let size_exceeded = self.max_bytes.is_some_and(|max| self.size >= max);
+        let day_elapsed = self.rotate_daily && Local::now().date_naive() != self.opened_on;
+        if !size_exceeded && !day_elapsed {
+            return Ok(());
+        }
+        let base_name = format!("{}.{}", self.path, self.opened_on.format("%Y-%m-%d"));
+        let rotated_name = Self::next_available_name(base_name);
+        std::fs::rename(&self.path, &rotated_name)?;
+        self.file = Self::open(&self.path)?;
+        self.size = 0;
+        self.opened_on = Local::now().date_naive();
+        Ok(())
+    }
+
+    /// Appends a numeric suffix to `base` if it already exists, so a second
+    /// size-triggered rotation on the same day doesn't clobber the first
+    /// rotated file.
+    fn next_available_name(base: String) -> String {
+        if !std::path::Path::new(&base).exists() {
+            return base;
+        }
+        for n in 1.. {
+            let candidate = format!("{}.{}", base, n);
+            if !std::path::Path::new(&candidate).exists() {
+                return candidate;
+            }
+        }
+        unreachable!()
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        self.rotate_if_needed()?;
+        writeln!(self.file, "{}", line)?;
+        self.size += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Writes one line per request to stdout and/or a rotating file, in either
+/// an Apache-combined-style text format (see [`LogFormat::Combined`]) or
+/// JSON-lines.
+pub struct AccessLog {
+    config: LogConfig,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl AccessLog {
+    pub fn new(config: LogConfig) -> std::io::Result<Self> {
+        let file = match &config.file {
+            Some(path) => Some(Mutex::new(RotatingFile::new(&config, path.clone())?)),
+            None => None,
+        };
+        Ok(AccessLog { config, file })
+    }
+
+    pub fn log(&self, entry: AccessLogEntry) {
+        if !self.config.stdout && self.file.is_none() {
+            return;
+        }
+        let line = match self.config.format {
+            LogFormat::Combined => format_combined(&entry),
+            LogFormat::Json => format_json(&entry),
+        };
+        if self.config.stdout {
+            println!("{}", line);
+        }
+        if let Some(file) = &self.file {
+            let mut file = file.lock().expect("access log mutex poisoned");
+            if let Err(e) = file.write_line(&line) {
+                log::error!("failed to write access log: {}", e);
+            }
+        }
+    }
+}
+
+fn format_combined(entry: &AccessLogEntry) -> String {
+    let date_time = Local::now().format("%d/%b/%Y:%H:%M:%S %z");
+    format!(
+        "{} [{}] \"{} {} HTTP/1.1\" {} {} {:.3}ms \"{}\"",
+        entry.remote_addr,
+        date_time,
+        entry.method,
+        entry.uri,
+        entry.status,
+        entry.bytes,
+        entry.latency.as_secs_f64() * 1000.0,
+        entry.chip_id.unwrap_or("-"),
+    )
+}
+
+fn format_json(entry: &AccessLogEntry) -> String {
+    let line = JsonLine {
+        remote_addr: entry.remote_addr.to_string(),
+        method: entry.method,
+        uri: entry.uri,
+        status: entry.status,
+        bytes: entry.bytes,
+        latency_ms: entry.latency.as_secs_f64() * 1000.0,
+        chip_id: entry.chip_id,
+    };
+    serde_json::to_string(&line).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e))
+}
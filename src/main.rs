@@ -1,63 +1,30 @@
-use lazy_static::lazy_static;
-use postgres::{Client, NoTls};
+mod album;
+mod auth;
+mod compression;
+mod config;
+mod database;
+mod error;
+mod logging;
+mod metrics;
+
+use config::Config;
+use database::CONNECTION_POOL;
+use error::Error;
+use logging::{AccessLog, AccessLogEntry};
+use metrics::METRICS;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::{collections::VecDeque, sync::Mutex};
 use std::{
     env,
-    io::Read,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
+    net::{IpAddr, SocketAddr},
     process::exit,
-    str::FromStr,
     sync::Arc,
-    thread,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
-use tiny_http::{Request, Response, Server};
 use ureq::serde_json;
 
-lazy_static! {
-    pub static ref CONNECTION_POOL: Mutex<VecDeque<Client>> = {
-        log::info!("empty pool created");
-        Mutex::new(VecDeque::<Client>::new())
-    };
-}
-
-impl CONNECTION_POOL {
-    pub fn initialise(&self, database_url: &str, pool_size: usize) -> Result<(), postgres::Error> {
-        let mut pool = self.lock().unwrap();
-        for _ in pool.len()..pool_size {
-            match Client::connect(database_url, NoTls) {
-                Ok(client) => pool.push_back(client),
-                Err(e) => {
-                    log::error!("failed to create connection: {:?}", e);
-                    return Err(e);
-                }
-            }
-        }
-        log::info!("connection pool populated, size: {}", pool_size);
-        Ok(())
-    }
-
-    pub fn get_client(&self) -> Result<Client, std::io::Error> {
-        let mut pool = self.lock().unwrap();
-        match pool.pop_front() {
-            Some(client) => Ok(client),
-            None => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "connection pool is exhausted",
-            )),
-        }
-    }
-
-    pub fn release_client(&self, client: Client) {
-        let mut pool = self.lock().unwrap();
-        pool.push_back(client);
-    }
-}
-
-const EPD_WIDTH: u32 = 600;
-const EPD_HEIGHT: u32 = 448;
-
 #[allow(dead_code)]
 struct AlbumRecord {
     item_id: String,
@@ -88,301 +55,516 @@ struct LogDoc {
     batVoltage: i32,
 }
 
-fn dispatch_response<R>(request: Request, mut response: Response<R>)
-where
-    R: Read,
-{
-    if !response
-        .headers()
-        .iter()
-        .any(|header| header.field.equiv("Content-Type"))
-    {
-        response = response.with_header(
-            tiny_http::Header::from_str("Content-Type: text/html; charset=UTF-8")
-                .expect("This should never fail"),
+#[derive(Debug, Serialize)]
+struct CreatedItem {
+    item_id: String,
+}
+
+fn dispatch_response(
+    remote_addr: SocketAddr,
+    method: &Method,
+    uri: &str,
+    received_at: Instant,
+    chip_id: Option<&str>,
+    access_log: &AccessLog,
+    mut response: Response<Body>,
+) -> Response<Body> {
+    if !response.headers().contains_key("Content-Type") {
+        response.headers_mut().insert(
+            "Content-Type",
+            "text/html; charset=UTF-8".parse().expect("This should never fail"),
         );
     }
-    response.add_header(
-        tiny_http::Header::from_str("Access-Control-Allow-Origin: *")
-            .expect("This should never fail"),
+    let headers = response.headers_mut();
+    headers.insert("Access-Control-Allow-Origin", "*".parse().unwrap());
+    headers.insert(
+        "Access-Control-Allow-Methods",
+        "OPTIONS, GET, POST, DELETE".parse().unwrap(),
     );
-    response.add_header(
-        tiny_http::Header::from_str("Access-Control-Allow-Methods: OPTIONS, GET")
-            .expect("This should never fail"),
-    );
-    response.add_header(
-        tiny_http::Header::from_str(
-            "Access-Control-Allow-Headers: Content-Type, Authorization, Data",
-        )
-        .expect("This should never fail"),
+    headers.insert(
+        "Access-Control-Allow-Headers",
+        "Content-Type, Authorization, Data".parse().unwrap(),
     );
-    let content_length = response.data_length().expect("This should not fail");
-    response.add_header(
-        tiny_http::Header::from_str(&format!("Content-Length: {}", content_length))
-            .expect("This should never fail"),
-    );
-    log_request(
-        &request,
-        response.status_code().0,
-        response.data_length().expect("This should not fail"),
-    );
-    if let Err(e) = request.respond(response) {
-        log::error!("could not send response: {}", e);
-    }
+    let status = response.status().as_u16();
+    let bytes = response.body().size_hint().lower() as usize;
+    access_log.log(AccessLogEntry {
+        remote_addr: remote_addr.ip(),
+        method: method.as_str(),
+        uri,
+        status,
+        bytes,
+        latency: received_at.elapsed(),
+        chip_id,
+    });
+    METRICS.record_request(status, bytes);
+    response
 }
 
-fn serve_error(request: Request, status_code: tiny_http::StatusCode, message: &str) {
-    let response = Response::new(
-        status_code,
-        vec![],
-        message.as_bytes(),
-        Some(message.as_bytes().len()),
-        None,
-    );
-    dispatch_response(request, response);
+fn serve_error(status_code: StatusCode, message: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status_code)
+        .body(Body::from(message))
+        .expect("This should never fail")
 }
 
-fn log_request(request: &tiny_http::Request, status: u16, size: usize) {
-    let remote_addr = request
-        .remote_addr()
-        .unwrap_or(&SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0))
-        .ip();
-    let date_time = chrono::Local::now().format("%d/%b/%Y:%H:%M:%S %z");
-    let method = request.method();
-    let uri = request.url();
-    let protocol = request.http_version();
-    let referer = request
+/// Handles `POST /album`. A `Content-Type: image/*` body is decoded and
+/// Floyd–Steinberg dithered down to the panel's packed palette; anything
+/// else (notably `application/octet-stream`) is assumed to already be in
+/// that packed format and stored as-is. `Portrait: true` marks the upload
+/// as a half-panel portrait image per the existing merge convention.
+async fn handle_album_upload(
+    request: Request<Body>,
+    dbclient: &mut database::PooledClient,
+    config: &Config,
+) -> Result<Response<Body>, Error> {
+    let content_type = request
         .headers()
-        .iter()
-        .find(|header| header.field.equiv("Referer"))
-        .map(|header| header.value.to_string())
-        .unwrap_or("-".to_string());
-    let user_agent = request
+        .get("Content-Type")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let portrait = request
         .headers()
-        .iter()
-        .find(|header| header.field.equiv("User-Agent"))
-        .map(|header| header.value.to_string())
-        .unwrap_or("-".to_string());
-    println!(
-        "{} [{}] \"{} {} {}\" {} {} \"{}\" \"{}\"",
-        remote_addr, date_time, method, uri, protocol, status, size, referer, user_agent
-    );
+        .get("Portrait")
+        .and_then(|h| h.to_str().ok())
+        .map(|v| v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .map_err(|_| Error::BadRequest("could not read request body"))?;
+    let data = if content_type.starts_with("image/") {
+        let image = image::load_from_memory(&body)
+            .map_err(|_| Error::BadRequest("could not decode uploaded image"))?;
+        // A portrait upload is a half-panel image per the merge routine below,
+        // which reads portrait records at half `panel_width`.
+        let width = if portrait {
+            config.panel_width / 2
+        } else {
+            config.panel_width
+        };
+        album::dither_to_palette(&image, width, config.panel_height)
+    } else {
+        body.to_vec()
+    };
+    let item_id = album::create(dbclient, data, portrait)
+        .await
+        .map_err(|e| db_error(dbclient, "create album item", e))?;
+    Ok(Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&CreatedItem { item_id })
+                .expect("CreatedItem is always serializable"),
+        ))
+        .expect("This should never fail"))
 }
 
-fn main() {
-    // for debugging purposes
-    if env::var_os("RUST_LOG").is_none() {
-        env::set_var("RUST_LOG", "info");
+/// Wraps a failed Postgres call into an [`Error`], poisoning `dbclient` when
+/// the failure looks like a broken connection rather than e.g. a constraint
+/// violation, so the pool discards and replaces it instead of recirculating
+/// a dead client.
+fn db_error(
+    dbclient: &mut database::PooledClient,
+    context: &'static str,
+    e: tokio_postgres::Error,
+) -> Error {
+    if error::is_connection_error(&e) {
+        dbclient.poison();
     }
-    if env::var_os("RUST_BACKTRACE").is_none() {
-        env::set_var("RUST_BACKTRACE", "1");
+    Error::Db(context, e)
+}
+
+async fn handle(
+    request: Request<Body>,
+    remote_addr: SocketAddr,
+    config: Arc<Config>,
+    access_log: Arc<AccessLog>,
+) -> Result<Response<Body>, std::convert::Infallible> {
+    let received_at = Instant::now();
+    let method = request.method().clone();
+    let uri = request.uri().path().to_string();
+    let respond = |response: Response<Body>, chip_id: Option<&str>| {
+        dispatch_response(
+            remote_addr,
+            &method,
+            &uri,
+            received_at,
+            chip_id,
+            &access_log,
+            response,
+        )
+    };
+
+    if method == Method::OPTIONS {
+        let response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .expect("This should never fail");
+        return Ok(respond(response, None));
     }
-    // dotenv::from_filename("secrets/.env").ok(); // used in dev only
-    env_logger::init();
-    if env::var("API_KEY").is_err() || env::var("POSTGRES_CONNECTION_STRING").is_err() {
-        log::error!("environment not configured");
-        return;
+
+    let (response, chip_id) = match try_handle(request, method.clone(), &uri, remote_addr, &config).await {
+        Ok((response, chip_id)) => (response, chip_id),
+        Err(e) => {
+            log::error!("(handle): {}", e);
+            let (status, message) = e.status_and_message();
+            (serve_error(status, message), None)
+        }
+    };
+    Ok(respond(response, chip_id.as_deref()))
+}
+
+/// Does the actual routing and request handling, funnelling every fallible
+/// step through `Error` instead of panicking, so a broken connection or a
+/// malformed upload degrades to a logged error response rather than taking
+/// down the worker task. Also returns the served frame's `item_id`, if any,
+/// so it can be logged as the request's `chip_id`.
+async fn try_handle(
+    request: Request<Body>,
+    method: Method,
+    uri: &str,
+    remote_addr: SocketAddr,
+    config: &Config,
+) -> Result<(Response<Body>, Option<String>), Error> {
+    let path = uri.trim_end_matches('/');
+    let is_album_route = path == "/album" || path.starts_with("/album/");
+    let required_scope = if is_album_route {
+        auth::Scope::Admin
+    } else {
+        auth::Scope::Read
+    };
+
+    let presented_key = request
+        .headers()
+        .get("Authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.to_string());
+
+    let mut dbclient = CONNECTION_POOL.get_client().await?;
+
+    let authorized = match &presented_key {
+        Some(key) => auth::authorize(&dbclient, key, required_scope)
+            .await
+            .map_err(|e| db_error(&mut dbclient, "check API key", e))?,
+        None => false,
+    };
+    if !authorized {
+        return Ok((serve_error(StatusCode::UNAUTHORIZED, "Unauthorized"), None));
     }
-    let server = Server::http("0.0.0.0:5000").expect("This should not fail");
-    println!(
-        "ðŸš€ Server started successfully, listening on {}",
-        server.server_addr()
-    );
-    let database_url = &env::var("POSTGRES_CONNECTION_STRING").expect("previously validated");
-    let pool_size = 2;
-    if let Err(e) = CONNECTION_POOL.initialise(database_url, pool_size) {
-        log::error!("failed to initialise connection pool: {:?}", e);
-        exit(1);
+
+    if method == Method::GET && path == "/metrics" {
+        return Ok((
+            Response::builder()
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(Body::from(METRICS.render()))
+                .expect("This should never fail"),
+            None,
+        ));
+    }
+
+    if method == Method::GET && path == "/album" {
+        let items = album::list(&dbclient)
+            .await
+            .map_err(|e| db_error(&mut dbclient, "list album", e))?;
+        return Ok((
+            Response::builder()
+                .header("Content-Type", "application/json")
+                .body(Body::from(
+                    serde_json::to_string(&items).expect("album list is always serializable"),
+                ))
+                .expect("This should never fail"),
+            None,
+        ));
+    }
+    if method == Method::POST && path == "/album" {
+        return handle_album_upload(request, &mut dbclient, config)
+            .await
+            .map(|response| (response, None));
+    }
+    if method == Method::DELETE {
+        if let Some(item_id) = path.strip_prefix("/album/") {
+            let deleted = album::delete(&dbclient, item_id)
+                .await
+                .map_err(|e| db_error(&mut dbclient, "delete album item", e))?;
+            return Ok((
+                match deleted {
+                    0 => serve_error(StatusCode::NOT_FOUND, "Not found"),
+                    _ => Response::builder()
+                        .status(StatusCode::NO_CONTENT)
+                        .body(Body::empty())
+                        .expect("This should never fail"),
+                },
+                None,
+            ));
+        }
+    }
+
+    if method != Method::GET {
+        return Ok((
+            serve_error(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+            None,
+        ));
+    }
+    if path != "/frame" {
+        return Ok((serve_error(StatusCode::NOT_FOUND, "Not found"), None));
+    }
+
+    let data_header = request
+        .headers()
+        .get("Data")
+        .and_then(|h| h.to_str().ok())
+        .map(|v| v.to_string());
+
+    let now = SystemTime::now();
+    let ts = match now.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(_) => panic!("SystemTime before UNIX EPOCH!"),
     };
-    let server = Arc::new(server);
-    for _ in 0..2 {
-        let server = server.clone();
-        thread::spawn(move || loop {
-            let request = match server.recv() {
-                Ok(r) => r,
-                Err(e) => {
-                    log::error!("could not receive request: {}", e);
-                    continue;
-                }
-            };
-            if request.method().as_str() == "OPTIONS" {
-                dispatch_response(request, Response::new_empty(tiny_http::StatusCode(204)));
-                continue;
-            }
-            if request.method().as_str() != "GET" {
-                serve_error(request, tiny_http::StatusCode(405), "Method not allowed");
-                continue;
-            }
-            let api_key = request
-                .headers()
-                .iter()
-                .find(|h| h.field.equiv("Authorization"))
-                .map(|h| h.value.to_string().split_off(7));
-            if api_key.is_none()
-                || api_key != Some(env::var("API_KEY").expect("previously validated"))
-            {
-                serve_error(request, tiny_http::StatusCode(401), "Unauthorized");
-                continue;
-            }
-            if request.url().trim_end_matches('/') != "/frame" {
-                serve_error(request, tiny_http::StatusCode(404), "Not found");
-                continue;
-            }
-            let now = SystemTime::now();
-            let ts = match now.duration_since(UNIX_EPOCH) {
-                Ok(duration) => duration.as_secs() as i64,
-                Err(_) => panic!("SystemTime before UNIX EPOCH!"),
-            };
-            let mut dbclient = match CONNECTION_POOL.get_client() {
-                Ok(dbclient) => dbclient,
-                Err(err) => {
-                    log::error!("(main): {err}");
-                    serve_error(request, tiny_http::StatusCode(500), "Internal server error");
-                    continue;
-                }
-            };
-            let album_records = match dbclient
-                .query(
-                    "WITH query_1 AS (
-                    UPDATE album
-                    SET ts = $1
-                    WHERE item_id = (
-                        SELECT item_id 
-                        FROM album 
-                        WHERE ts = (SELECT MIN(ts) FROM album) 
-                        ORDER BY RANDOM() 
-                        LIMIT 1
-                    )
-                    RETURNING item_id, portrait
-                ),
-                query_2 AS (
-                    UPDATE album
-                    SET ts = $1
-                    WHERE (SELECT portrait FROM query_1) = true AND
-                        item_id = (
-                            SELECT item_id 
-                            FROM album 
-                            WHERE item_id != (SELECT item_id FROM query_1) AND
-                            portrait = true ORDER BY random() LIMIT 1
-                        )
-                    RETURNING item_id
-                )
-                SELECT item_id, ts, portrait, data
+    let query_started = Instant::now();
+    let album_records = dbclient
+        .query(
+            "WITH query_1 AS (
+            UPDATE album
+            SET ts = $1
+            WHERE item_id = (
+                SELECT item_id
                 FROM album
-                WHERE item_id IN (
-                    SELECT item_id
-                    FROM query_1
-                    UNION
+                WHERE ts = (SELECT MIN(ts) FROM album)
+                ORDER BY RANDOM()
+                LIMIT 1
+            )
+            RETURNING item_id, portrait
+        ),
+        query_2 AS (
+            UPDATE album
+            SET ts = $1
+            WHERE (SELECT portrait FROM query_1) = true AND
+                item_id = (
                     SELECT item_id
-                    FROM query_2
+                    FROM album
+                    WHERE item_id != (SELECT item_id FROM query_1) AND
+                    portrait = true ORDER BY random() LIMIT 1
                 )
-                ORDER BY random()",
-                    &[&ts],
-                )
-                .map(|records| {
-                    let mut album_records = Vec::new();
-                    for row in records.iter() {
-                        let record = AlbumRecord {
-                            item_id: row.get(0),
-                            ts: row.get(1),
-                            portrait: row.get(2),
-                            data: row.get(3),
-                        };
-                        album_records.push(record);
-                    }
-                    album_records
-                }) {
-                Ok(records) => records,
-                Err(e) => {
-                    log::error!("could not get record(s): {}", e);
-                    serve_error(request, tiny_http::StatusCode(500), "Internal server error");
-                    continue;
-                }
+            RETURNING item_id
+        )
+        SELECT item_id, ts, portrait, data
+        FROM album
+        WHERE item_id IN (
+            SELECT item_id
+            FROM query_1
+            UNION
+            SELECT item_id
+            FROM query_2
+        )
+        ORDER BY random()",
+            &[&ts],
+        )
+        .await
+        .map(|records| {
+            records
+                .iter()
+                .map(|row| AlbumRecord {
+                    item_id: row.get(0),
+                    ts: row.get(1),
+                    portrait: row.get(2),
+                    data: row.get(3),
+                })
+                .collect::<Vec<_>>()
+        })
+        .map_err(|e| db_error(&mut dbclient, "query frame record", e))?;
+    METRICS.record_db_query(query_started.elapsed());
+    if album_records.is_empty() {
+        return Ok((
+            serve_error(StatusCode::SERVICE_UNAVAILABLE, "No album items available"),
+            None,
+        ));
+    }
+    let data = match album_records.iter().filter(|r| r.portrait).count() {
+        0 => album_records[0].data.clone(),
+        count => {
+            let w = config.panel_width as usize / 2; // 2 pixels are packed per byte
+            let h = config.panel_height as usize;
+            let xs1 = &album_records[0].data;
+            let xs2: &Vec<u8> = &Vec::new();
+            let xs2 = match count {
+                1 => xs2,
+                2 => &album_records[1].data,
+                _ => unreachable!(),
+            };
+            let offset = match count {
+                1 => w / 4,
+                2 => w / 2,
+                _ => unreachable!(),
             };
-            let data = match album_records.iter().filter(|r| r.portrait).count() {
-                0 => album_records[0].data.clone(),
-                count => {
-                    let w = EPD_WIDTH as usize / 2; // 2 pixels are packed per byte
-                    let h = EPD_HEIGHT as usize;
-                    let xs1 = &album_records[0].data;
-                    let xs2: &Vec<u8> = &Vec::new();
-                    let xs2 = match count {
-                        1 => xs2,
-                        2 => &album_records[1].data,
-                        _ => unreachable!(),
-                    };
-                    let offset = match count {
-                        1 => w / 4,
-                        2 => w / 2,
-                        _ => unreachable!(),
-                    };
-                    let mut xs: Vec<u8> = vec![0b00010001; w * h]; // 0b00010001 = white
-                    for y in 0..h {
-                        for x in 0..(w / 2) {
-                            let i = y * (w / 2) + x;
-                            if (x == 0) & (count == 2) {
-                                xs[y * w + x] = xs1[i];
-                                xs[y * w + x + offset] = (1 << 4) | (0b00001111 & xs2[i]);
-                            } else if (x == (w / 2 - 1)) & (count == 2) {
-                                xs[y * w + x] = (1 << 0) | (0b11110000 & xs1[i]); // 1 = white
-                                xs[y * w + x + offset] = xs2[i];
-                            } else if count == 2 {
-                                xs[y * w + x] = xs1[i];
-                                xs[y * w + x + offset] = xs2[i];
-                            } else if count == 1 {
-                                xs[y * w + x + offset] = xs1[i];
-                            }
-                        }
+            let mut xs: Vec<u8> = vec![0b00010001; w * h]; // 0b00010001 = white
+            for y in 0..h {
+                for x in 0..(w / 2) {
+                    let i = y * (w / 2) + x;
+                    if (x == 0) & (count == 2) {
+                        xs[y * w + x] = xs1[i];
+                        xs[y * w + x + offset] = (1 << 4) | (0b00001111 & xs2[i]);
+                    } else if (x == (w / 2 - 1)) & (count == 2) {
+                        xs[y * w + x] = (1 << 0) | (0b11110000 & xs1[i]); // 1 = white
+                        xs[y * w + x + offset] = xs2[i];
+                    } else if count == 2 {
+                        xs[y * w + x] = xs1[i];
+                        xs[y * w + x + offset] = xs2[i];
+                    } else if count == 1 {
+                        xs[y * w + x + offset] = xs1[i];
                     }
-                    xs
                 }
-            };
-            let item_id = Some(album_records[0].item_id.to_string());
-            let mut item_id_2 = None;
-            if album_records.iter().filter(|r| r.portrait).count() == 2 {
-                item_id_2 = Some(album_records[1].item_id.to_string());
             }
-            let uploaded_data: LogDoc = request
-                .headers()
-                .iter()
-                .find(|h| h.field.equiv("Data"))
-                .map(|h| serde_json::from_str(h.value.as_ref()).unwrap_or_default())
-                .unwrap_or_default();
-            let record = TelemetryRecord {
-                ts,
-                item_id: item_id.clone(),
-                item_id_2: item_id_2.clone(),
-                bat_voltage: uploaded_data.batVoltage,
-                boot_code: uploaded_data.bootCode,
-                remote_addr: vec![request
-                    .remote_addr()
-                    .expect("always some for tcp listeners")
-                    .ip()],
-            };
-            dbclient.execute(
-                    "
-                    INSERT INTO telemetry (ts, item_id, item_id_2, bat_voltage, boot_code, remote_addr) 
-                    VALUES ($1, $2, $3, $4, $5, $6)", 
-                    &[
-                        &record.ts,
-                        &record.item_id,
-                        &record.item_id_2,
-                        &record.bat_voltage,
-                        &record.boot_code,
-                        &record.remote_addr,
-                    ],
-                ).expect("unable to insert telemetry record");
-            CONNECTION_POOL.release_client(dbclient);
-            let response = Response::from_data(data)
-                .with_chunked_threshold(134401)
-                .with_header(
-                    tiny_http::Header::from_str("Content-Type: application/octet-stream")
-                        .expect("This should never fail"),
-                );
-            dispatch_response(request, response);
-        });
+            xs
+        }
+    };
+    let item_id = Some(album_records[0].item_id.to_string());
+    let mut item_id_2 = None;
+    if album_records.iter().filter(|r| r.portrait).count() == 2 {
+        item_id_2 = Some(album_records[1].item_id.to_string());
+    }
+    let uploaded_data: LogDoc = data_header
+        .map(|v| serde_json::from_str(&v).unwrap_or_default())
+        .unwrap_or_default();
+    let record = TelemetryRecord {
+        ts,
+        item_id: item_id.clone(),
+        item_id_2: item_id_2.clone(),
+        bat_voltage: uploaded_data.batVoltage,
+        boot_code: uploaded_data.bootCode,
+        remote_addr: vec![remote_addr.ip()],
+    };
+    dbclient
+        .execute(
+            "
+            INSERT INTO telemetry (ts, item_id, item_id_2, bat_voltage, boot_code, remote_addr)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            &[
+                &record.ts,
+                &record.item_id,
+                &record.item_id_2,
+                &record.bat_voltage,
+                &record.boot_code,
+                &record.remote_addr,
+            ],
+        )
+        .await
+        .map_err(|e| db_error(&mut dbclient, "insert telemetry record", e))?;
+    drop(dbclient);
+    if let Some(item_id) = &record.item_id {
+        METRICS.record_frame_telemetry(item_id, ts, record.bat_voltage, record.boot_code);
+    }
+    let accept_encoding = request
+        .headers()
+        .get("Accept-Encoding")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("");
+    let (data, content_encoding) = compression::negotiate(data, accept_encoding, &config.compression);
+    let mut response_builder =
+        Response::builder().header("Content-Type", "application/octet-stream");
+    if let Some(content_encoding) = content_encoding {
+        response_builder = response_builder.header("Content-Encoding", content_encoding);
+    }
+    let response = response_builder
+        .body(Body::from(data))
+        .expect("This should never fail");
+    Ok((response, item_id))
+}
+
+async fn run(config: Arc<Config>, access_log: Arc<AccessLog>) {
+    if env::var("POSTGRES_CONNECTION_STRING").is_err() {
+        log::error!("environment not configured");
+        return;
+    }
+    let database_url = &env::var("POSTGRES_CONNECTION_STRING").expect("previously validated");
+    if let Err(e) = CONNECTION_POOL.initialise(database_url, config.pool_size).await {
+        log::error!("failed to initialise connection pool: {:?}", e);
+        exit(1);
+    };
+
+    let addr = config.bind_address;
+    let make_svc = make_service_fn(move |conn: &hyper::server::conn::AddrStream| {
+        let remote_addr = conn.remote_addr();
+        let config = config.clone();
+        let access_log = access_log.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(service_fn(move |req| {
+                handle(req, remote_addr, config.clone(), access_log.clone())
+            }))
+        }
+    });
+
+    let server = Server::bind(&addr)
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal());
+    println!("🚀 Server started successfully, listening on {}", addr);
+
+    if let Err(e) = server.await {
+        log::error!("server error: {}", e);
+        exit(1);
+    }
+    log::info!("all in-flight requests drained, closing connection pool");
+    CONNECTION_POOL.shutdown().await;
+    log::info!("connection pool closed, server stopped");
+}
+
+/// Resolves on SIGINT or (on Unix) SIGTERM, letting `run`'s `server.await`
+/// finish in-flight requests via `with_graceful_shutdown` before the
+/// connection pool is drained, instead of the process being killed mid-response.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install SIGINT handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    log::info!("shutdown signal received, draining in-flight requests");
+}
+
+fn main() {
+    // for debugging purposes
+    if env::var_os("RUST_LOG").is_none() {
+        env::set_var("RUST_LOG", "info");
     }
-    loop {
-        thread::park();
+    if env::var_os("RUST_BACKTRACE").is_none() {
+        env::set_var("RUST_BACKTRACE", "1");
     }
+    dotenv::from_filename("secrets/.env").ok();
+    env_logger::init();
+
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            log::error!("invalid configuration: {}", e);
+            exit(1);
+        }
+    };
+    let access_log = match AccessLog::new(config.access_log.clone()) {
+        Ok(access_log) => Arc::new(access_log),
+        Err(e) => {
+            log::error!("could not open access log: {}", e);
+            exit(1);
+        }
+    };
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(config.worker_threads)
+        .enable_all()
+        .build()
+        .expect("failed to build tokio runtime");
+    runtime.block_on(run(config, access_log));
 }
@@ -0,0 +1,141 @@
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+#[derive(Default)]
+struct Histogram {
+    count: AtomicU64,
+    sum_millis: AtomicU64,
+}
+
+impl Histogram {
+    fn observe(&self, duration: Duration) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_millis
+            .fetch_add(duration.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str, out: &mut String) {
+        let count = self.count.load(Ordering::Relaxed);
+        let sum_millis = self.sum_millis.load(Ordering::Relaxed);
+        let _ = writeln!(out, "# TYPE {name} summary");
+        let _ = writeln!(out, "{name}_sum {}", sum_millis as f64 / 1000.0);
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// Per-frame telemetry last observed on a `/frame` request, used to drive
+/// the `frame_last_served_timestamp_seconds` and `frame_battery_voltage` /
+/// `frame_boot_code` gauges.
+#[derive(Clone, Copy, Default)]
+struct FrameTelemetry {
+    last_served_ts: i64,
+    bat_voltage: i32,
+    boot_code: i32,
+}
+
+/// Process-wide counters and gauges rendered by `GET /metrics` in the
+/// Prometheus text exposition format. Updated with plain atomics/mutexes
+/// from the hot path; rendering holds the same mutexes while it reads them.
+#[derive(Default)]
+pub struct Metrics {
+    requests_by_status: Mutex<HashMap<u16, u64>>,
+    response_bytes_total: AtomicU64,
+    pool_acquire_waits: Histogram,
+    pool_acquire_timeouts: AtomicU64,
+    db_query_latency: Histogram,
+    frames: Mutex<HashMap<String, FrameTelemetry>>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, status: u16, bytes: usize) {
+        *self
+            .requests_by_status
+            .lock()
+            .expect("metrics mutex poisoned")
+            .entry(status)
+            .or_insert(0) += 1;
+        self.response_bytes_total
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_pool_acquire(&self, wait: Duration, timed_out: bool) {
+        self.pool_acquire_waits.observe(wait);
+        if timed_out {
+            self.pool_acquire_timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_db_query(&self, latency: Duration) {
+        self.db_query_latency.observe(latency);
+    }
+
+    pub fn record_frame_telemetry(&self, item_id: &str, ts: i64, bat_voltage: i32, boot_code: i32) {
+        self.frames.lock().expect("metrics mutex poisoned").insert(
+            item_id.to_string(),
+            FrameTelemetry {
+                last_served_ts: ts,
+                bat_voltage,
+                boot_code,
+            },
+        );
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE http_requests_total counter");
+        for (status, count) in self
+            .requests_by_status
+            .lock()
+            .expect("metrics mutex poisoned")
+            .iter()
+        {
+            let _ = writeln!(out, "http_requests_total{{status=\"{status}\"}} {count}");
+        }
+        let _ = writeln!(out, "# TYPE http_response_bytes_total counter");
+        let _ = writeln!(
+            out,
+            "http_response_bytes_total {}",
+            self.response_bytes_total.load(Ordering::Relaxed)
+        );
+        self.pool_acquire_waits
+            .render("pool_acquire_wait_seconds", &mut out);
+        let _ = writeln!(out, "# TYPE pool_acquire_timeouts_total counter");
+        let _ = writeln!(
+            out,
+            "pool_acquire_timeouts_total {}",
+            self.pool_acquire_timeouts.load(Ordering::Relaxed)
+        );
+        self.db_query_latency
+            .render("db_query_latency_seconds", &mut out);
+
+        let _ = writeln!(out, "# TYPE frame_last_served_timestamp_seconds gauge");
+        let _ = writeln!(out, "# TYPE frame_battery_voltage gauge");
+        let _ = writeln!(out, "# TYPE frame_boot_code gauge");
+        for (item_id, telemetry) in self.frames.lock().expect("metrics mutex poisoned").iter() {
+            let _ = writeln!(
+                out,
+                "frame_last_served_timestamp_seconds{{item_id=\"{item_id}\"}} {}",
+                telemetry.last_served_ts
+            );
+            let _ = writeln!(
+                out,
+                "frame_battery_voltage{{item_id=\"{item_id}\"}} {}",
+                telemetry.bat_voltage
+            );
+            let _ = writeln!(
+                out,
+                "frame_boot_code{{item_id=\"{item_id}\"}} {}",
+                telemetry.boot_code
+            );
+        }
+        out
+    }
+}